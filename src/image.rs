@@ -1,4 +1,3 @@
-use leptos::logging;
 use crate::optimizer::*;
 
 use leptos::prelude::*;
@@ -9,20 +8,35 @@ use base64::{engine::general_purpose, Engine as _};
  * Renders an optimized static image with optional blur placeholder and preload.
  *
  * The width/height properties ensure the layout space is reserved from the start,
- * preventing content shift when the image or placeholder loads.
+ * preventing content shift when the image or placeholder loads. Only one of
+ * `width`/`height` is required; the other is derived from the source's
+ * intrinsic aspect ratio.
  */
 #[component]
 pub fn Image(
-    /// Image source. Should be path relative to root.
+    /// Image source: a path relative to root, or an `http(s)://` URL whose
+    /// host is on the server's remote-image allowlist.
     #[prop(into)]
     src: String,
-    /// Resize image height (final image), maintains aspect ratio relative to `width`.
-    height: u32,
-    /// Resize image width (final image), maintains aspect ratio relative to `height`.
-    width: u32,
+    /// Resize image height (final image). If omitted, derived from `width`
+    /// and the source's intrinsic aspect ratio.
+    #[prop(optional)]
+    height: Option<u32>,
+    /// Resize image width (final image). If omitted, derived from `height`
+    /// and the source's intrinsic aspect ratio.
+    #[prop(optional)]
+    width: Option<u32>,
     /// Image quality (0-100).
     #[prop(default = 75_u8)]
     quality: u8,
+    /// Target encoding for the optimized image (WebP, Avif, Jpeg, Png).
+    /// Ignored when `crop` is set: cropped images are always WebP.
+    #[prop(default = ImageFormat::WebP)]
+    format: ImageFormat,
+    /// Crop to `width`x`height` instead of resizing, anchored on this side of
+    /// the source image. Leave unset to resize (preserving aspect ratio) instead.
+    #[prop(optional)]
+    crop: Option<Gravity>,
     /// Whether to add a blur placeholder before the real image loads.
     #[prop(default = true)]
     blur: bool,
@@ -39,15 +53,41 @@ pub fn Image(
     #[prop(into, optional)]
     class: MaybeProp<String>,
 ) -> impl IntoView {
-    // If remote (http/https), skip optimization and just return a plain <img>.
-    if src.starts_with("http") {
-        logging::debug_warn!("Image component only supports static images.");
+    // Remote (http/https) sources are downloaded, optimized and cached by
+    // `ImageOptimizer` the same as local ones, provided their host is on the
+    // server's remote-image allowlist. Optimization is opt-in for remote
+    // sources, so one that isn't allowlisted falls back to a plain,
+    // unoptimized `<img>` passthrough below rather than erroring through the
+    // cache route.
+
+    // A local source's intrinsic size is a synchronous, header-only disk
+    // read, so it's resolved up front rather than through the cache
+    // resource. Remote sources aren't locally readable this way, so a
+    // caller passing neither `width` nor `height` for one gets (0, 0).
+    #[cfg(feature = "ssr")]
+    let metadata = (!is_remote_src(&src))
+        .then(|| expect_context::<ImageOptimizer>().read_source_metadata(&src).ok())
+        .flatten();
+    #[cfg(not(feature = "ssr"))]
+    let metadata: Option<ImageMetadata> = None;
+    let (width, height) = resolve_dimensions(width, height, metadata.as_ref());
+
+    // SSR resolves this from the real allowlist; the client has no
+    // `ImageOptimizer` context to check it against, so it conservatively
+    // assumes a remote source isn't allowlisted — the real decision has
+    // already shipped in the server-rendered markup by the time this runs.
+    #[cfg(feature = "ssr")]
+    let remote_allowed = expect_context::<ImageOptimizer>().is_remote_src_allowed(&src);
+    #[cfg(not(feature = "ssr"))]
+    let remote_allowed = false;
+
+    if is_remote_src(&src) && !remote_allowed {
         let loading = if lazy { "lazy" } else { "eager" };
         return view! {
             <img
-                src=src
+                src=src.clone()
                 alt=alt
-                class=class.get()
+                class=move || class.get()
                 width=width
                 height=height
                 decoding="async"
@@ -71,11 +111,20 @@ pub fn Image(
 
     let opt_image = StoredValue::new(CachedImage {
         src: src.clone(),
-        option: CachedImageOption::Resize(Resize {
-            quality,
-            width,
-            height,
-        }),
+        option: match crop {
+            Some(gravity) => CachedImageOption::Crop(Crop {
+                quality,
+                width,
+                height,
+                gravity,
+            }),
+            None => CachedImageOption::Resize(Resize {
+                quality,
+                width,
+                height,
+                format,
+            }),
+        },
     });
 
     // We fetch the global image cache resource
@@ -86,9 +135,9 @@ pub fn Image(
         <Suspense fallback=move || {
             view! {
                 // If you prefer, you could do a placeholder gray box, spinner, etc.
-                <div style=move || {
-                    format!("width: {}px; height: {}px; background-color: #f0f0f0;", width, height)
-                } />
+                <div style=format!(
+                    "width: {width}px; height: {height}px; background-color: #f0f0f0;"
+                ) />
             }
         }>
             // Once the resource is ready, we show the real or blurred image
@@ -152,6 +201,31 @@ pub fn Image(
     }.into_any()
 }
 
+/// Fill in a missing `width`/`height` from the source's intrinsic aspect
+/// ratio. Without metadata, a single given side is used for both (a 1:1
+/// guess), and `(0, 0)` is returned if neither side is given.
+fn resolve_dimensions(
+    width: Option<u32>,
+    height: Option<u32>,
+    metadata: Option<&ImageMetadata>,
+) -> (u32, u32) {
+    match (width, height, metadata) {
+        (Some(width), Some(height), _) => (width, height),
+        (Some(width), None, Some(meta)) => {
+            let height = (width as f64 * meta.height as f64 / meta.width as f64).round() as u32;
+            (width, height)
+        }
+        (None, Some(height), Some(meta)) => {
+            let width = (height as f64 * meta.width as f64 / meta.height as f64).round() as u32;
+            (width, height)
+        }
+        (None, None, Some(meta)) => (meta.width, meta.height),
+        (Some(width), None, None) => (width, width),
+        (None, Some(height), None) => (height, height),
+        (None, None, None) => (0, 0),
+    }
+}
+
 enum SvgImage {
     InMemory(String),
     Request(String),
@@ -1,9 +1,109 @@
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "ssr")]
+use once_cell::sync::Lazy;
+#[cfg(feature = "ssr")]
+use std::collections::HashMap;
+#[cfg(feature = "ssr")]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "ssr")]
+use std::sync::Arc;
+
 /**
  * Service for creating cached/optimized images!
  */
 
+/// Tracks in-flight cache writes so that concurrent requests for the same
+/// `CachedImage` don't encode it more than once and never observe a
+/// partially-written file. A plain `std::sync::RwLock` rather than
+/// `tokio::sync::RwLock`: every critical section here is synchronous and
+/// brief (no `.await` while holding it), which lets `InProgressGuard::drop`
+/// clear its entry without needing a blocking or async lock.
+#[cfg(feature = "ssr")]
+static IN_PROGRESS: Lazy<std::sync::RwLock<HashMap<std::path::PathBuf, Arc<CacheStatus>>>> =
+    Lazy::new(|| std::sync::RwLock::new(HashMap::new()));
+
+/// Handle other tasks can await while a cache entry is being written.
+#[cfg(feature = "ssr")]
+#[derive(Debug, Default)]
+struct CacheStatus {
+    notify: tokio::sync::Notify,
+    done: AtomicBool,
+    // Set once, alongside `done`, to the leader's encode result (`Err` holds
+    // the error's `Display` text, since `CreateImageError` isn't `Clone`) so
+    // followers can propagate a failed encode instead of a false cache hit.
+    result: std::sync::Mutex<Option<Result<(), String>>>,
+}
+
+#[cfg(feature = "ssr")]
+impl CacheStatus {
+    /// Wait until the task that owns this entry has finished writing it,
+    /// then return its result (`Ok(())` only if the encode actually succeeded).
+    async fn wait_until_done(&self) -> Result<(), CreateImageError> {
+        let notified = self.notify.notified();
+        tokio::pin!(notified);
+        // Register before checking `done` so a `mark_done` that races with
+        // this check can't be missed between the check and the await.
+        notified.as_mut().enable();
+        if !self.done.load(Ordering::Acquire) {
+            notified.await;
+        }
+        match self.result.lock().unwrap().clone() {
+            Some(Ok(())) => Ok(()),
+            Some(Err(message)) => Err(CreateImageError::ConcurrentWriteError(message)),
+            None => unreachable!("done is only set alongside result"),
+        }
+    }
+
+    fn mark_done(&self, result: Result<(), String>) {
+        *self.result.lock().unwrap() = Some(result);
+        self.done.store(true, Ordering::Release);
+        self.notify.notify_waiters();
+    }
+}
+
+/// Clears this entry's `IN_PROGRESS` slot and marks its `CacheStatus` done on
+/// drop, so a cancelled leader (client disconnect, a `select!`/timeout
+/// wrapping the call to `create_image`, or any other future cancellation —
+/// all routine in a real HTTP server) still wakes any followers instead of
+/// leaving them waiting on a status that's never marked done. `finish`
+/// records the real outcome before drop runs; if it's never called (the
+/// future was dropped mid-encode), drop relays a cancellation error instead
+/// of silently leaving the entry, and every future waiter, stuck forever.
+#[cfg(feature = "ssr")]
+struct InProgressGuard {
+    save_path: std::path::PathBuf,
+    status: Arc<CacheStatus>,
+    result: Option<Result<(), String>>,
+}
+
+#[cfg(feature = "ssr")]
+impl InProgressGuard {
+    fn new(save_path: std::path::PathBuf, status: Arc<CacheStatus>) -> Self {
+        Self {
+            save_path,
+            status,
+            result: None,
+        }
+    }
+
+    fn finish(&mut self, result: &Result<(), CreateImageError>) {
+        self.result = Some(result.as_ref().map(|_| ()).map_err(|err| err.to_string()));
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl Drop for InProgressGuard {
+    fn drop(&mut self) {
+        IN_PROGRESS.write().unwrap().remove(&self.save_path);
+        let result = self
+            .result
+            .take()
+            .unwrap_or_else(|| Err("cache write cancelled before completing".to_string()));
+        self.status.mark_done(result);
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, Hash)]
 pub struct CachedImage {
     pub src: String,
@@ -14,6 +114,8 @@ pub struct CachedImage {
 pub enum CachedImageOption {
     #[serde(rename = "r")]
     Resize(Resize),
+    #[serde(rename = "c")]
+    Crop(Crop),
     #[serde(rename = "b")]
     Blur(Blur),
 }
@@ -26,6 +128,73 @@ pub struct Resize {
     pub height: u32,
     #[serde(rename = "q")]
     pub quality: u8,
+    #[serde(rename = "f", default)]
+    pub format: ImageFormat,
+}
+
+/// Target encoding for an optimized image.
+///
+/// `WebP` is encoded through the `webp` crate, `Avif` through a dedicated
+/// AVIF encoder, and `Jpeg`/`Png` through `image`'s native encoders.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Serialize, Hash)]
+pub enum ImageFormat {
+    #[default]
+    #[serde(rename = "webp")]
+    WebP,
+    #[serde(rename = "avif")]
+    Avif,
+    #[serde(rename = "jpeg")]
+    Jpeg,
+    #[serde(rename = "png")]
+    Png,
+}
+
+impl ImageFormat {
+    pub(crate) fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::WebP => "webp",
+            ImageFormat::Avif => "avif",
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Png => "png",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, Hash)]
+pub struct Crop {
+    #[serde(rename = "w")]
+    pub width: u32,
+    #[serde(rename = "h")]
+    pub height: u32,
+    #[serde(rename = "q")]
+    pub quality: u8,
+    #[serde(rename = "g", default)]
+    pub gravity: Gravity,
+}
+
+/// Which part of the source image to keep when cropping to a fixed aspect
+/// ratio discards the rest.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Serialize, Hash)]
+pub enum Gravity {
+    #[default]
+    #[serde(rename = "c")]
+    Center,
+    #[serde(rename = "n")]
+    North,
+    #[serde(rename = "ne")]
+    NorthEast,
+    #[serde(rename = "e")]
+    East,
+    #[serde(rename = "se")]
+    SouthEast,
+    #[serde(rename = "s")]
+    South,
+    #[serde(rename = "sw")]
+    SouthWest,
+    #[serde(rename = "w")]
+    West,
+    #[serde(rename = "nw")]
+    NorthWest,
 }
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, Hash)]
@@ -52,6 +221,22 @@ pub enum CreateImageError {
     JoinError(#[from] tokio::task::JoinError),
     #[error("IO Error: {0}")]
     IOError(#[from] std::io::Error),
+    #[error("Avif Error: {0}")]
+    AvifError(String),
+    #[error("Json Error: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("Remote Image Error: {0}")]
+    RemoteError(String),
+    #[error("Concurrent Write Error: {0}")]
+    ConcurrentWriteError(String),
+    #[error("Invalid Image Dimensions: {0}")]
+    InvalidDimensions(String),
+}
+
+/// Whether a `CachedImage::src` refers to a remote image that needs to be
+/// downloaded rather than read from `root_file_path`.
+pub(crate) fn is_remote_src(src: &str) -> bool {
+    src.starts_with("http://") || src.starts_with("https://")
 }
 
 impl CachedImage {
@@ -64,37 +249,25 @@ impl CachedImage {
 
     #[cfg(feature = "ssr")]
     pub fn get_file_path(&self) -> String {
-        use base64::{engine::general_purpose, Engine as _};
-        // I'm worried this name will become too long.
-        // names are limited to 255 bytes on most filesystems.
-
-        let encode = serde_qs::to_string(&self).unwrap();
-        let encode = general_purpose::STANDARD.encode(encode);
-
-        let mut path = path_from_segments(vec!["cache/image", &encode, &self.src]);
-
-        if let CachedImageOption::Resize { .. } = self.option {
-            path.set_extension("webp");
-        } else {
-            path.set_extension("svg");
-        };
-
-        path.as_path().to_string_lossy().to_string()
+        // Unlike `ImageOptimizer::get_file_path`, this method has no root to
+        // resolve `src` against, so it can't reliably find the same file on
+        // disk to hash in its mtime — doing so anyway would silently produce
+        // a different hash (and thus a different path) than the one
+        // `ImageOptimizer` actually writes to whenever CWD != root. It
+        // deliberately hashes on the spec alone instead, so callers without
+        // an `ImageOptimizer` (e.g. these tests) get a stable path rather
+        // than one that's only sometimes correct.
+        build_cache_file_path(self, None)
     }
 
     #[allow(dead_code)]
     #[cfg(feature = "ssr")]
-    // TODO: Fix this. Super Yuck.
+    /// Reconstructs a `CachedImage` from its hashed path by reading the spec
+    /// sidecar written alongside it (the filename itself carries no info).
     pub(crate) fn from_file_path(path: &str) -> Option<Self> {
-        use base64::{engine::general_purpose, Engine as _};
-        path.split('/')
-            .filter_map(|s| {
-                general_purpose::STANDARD
-                    .decode(s)
-                    .ok()
-                    .and_then(|s| String::from_utf8(s).ok())
-            })
-            .find_map(|encoded| serde_qs::from_str(&encoded).ok())
+        let sidecar = sidecar_path(std::path::Path::new(path));
+        let contents = std::fs::read(sidecar).ok()?;
+        serde_json::from_slice(&contents).ok()
     }
 
     #[cfg(feature = "ssr")]
@@ -105,32 +278,59 @@ impl CachedImage {
     }
 }
 
+/// Cap on how many bytes of a remote image we'll buffer before decoding, so a
+/// malicious or huge upstream response can't exhaust memory.
+#[cfg(feature = "ssr")]
+const MAX_REMOTE_DOWNLOAD_BYTES: u64 = 20 * 1024 * 1024;
+
 #[cfg(feature = "ssr")]
 #[derive(Debug, Clone)]
 pub struct ImageOptimizer {
     root_file_path: String,
     // cache_prefix: String,
     semaphore: std::sync::Arc<tokio::sync::Semaphore>,
+    allowed_remote_hosts: std::sync::Arc<Vec<String>>,
 }
 
 #[cfg(feature = "ssr")]
 impl ImageOptimizer {
+    /// Remote (`http(s)://`) sources are rejected by default — call
+    /// `with_allowed_remote_hosts` to opt in to fetching from specific hosts.
     pub fn new(root_file_path: String, parallelism: usize) -> Self {
         let semaphore = tokio::sync::Semaphore::new(parallelism);
         let semaphore = std::sync::Arc::new(semaphore);
         Self {
             root_file_path,
             semaphore,
+            allowed_remote_hosts: std::sync::Arc::new(Vec::new()),
         }
     }
 
+    /// Opts this optimizer into fetching remote (`http(s)://`) sources whose
+    /// host is in `allowed_remote_hosts` (the SSRF allowlist: only exact host
+    /// matches are fetched and optimized).
+    pub fn with_allowed_remote_hosts(mut self, allowed_remote_hosts: Vec<String>) -> Self {
+        self.allowed_remote_hosts = std::sync::Arc::new(allowed_remote_hosts);
+        self
+    }
+
+    /// Whether `src` (assumed remote) is on this optimizer's allowlist. Used
+    /// by `Image` to decide whether a remote source can be routed through
+    /// optimization, or must fall back to an unoptimized `<img src>`
+    /// passthrough rather than erroring through the cache route.
+    pub(crate) fn is_remote_src_allowed(&self, src: &str) -> bool {
+        reqwest::Url::parse(src)
+            .ok()
+            .is_some_and(|url| validate_remote_host(&url, &self.allowed_remote_hosts).is_ok())
+    }
+
     pub async fn create_image(&self, cache_image: &CachedImage) -> Result<bool, CreateImageError> {
         let root = self.root_file_path.as_str();
         {
-            let option = if let CachedImageOption::Resize(_) = cache_image.option {
-                "Resize"
-            } else {
-                "Blur"
+            let option = match cache_image.option {
+                CachedImageOption::Resize(_) => "Resize",
+                CachedImageOption::Crop(_) => "Crop",
+                CachedImageOption::Blur(_) => "Blur",
             };
             tracing::debug!("Creating {option} image for {}", &cache_image.src);
         }
@@ -138,29 +338,105 @@ impl ImageOptimizer {
         let relative_path_created = self.get_file_path(&cache_image);
 
         let save_path = path_from_segments(vec![root, &relative_path_created]);
-        let absolute_src_path = path_from_segments(vec![root, &cache_image.src]);
 
         if file_exists(&save_path).await {
-            Ok(false)
+            // The file appears as soon as its leader starts writing it, so it
+            // only counts as a cache hit once no encode of it is in progress.
+            if !IN_PROGRESS.read().unwrap().contains_key(&save_path) {
+                return Ok(false);
+            }
+        }
+
+        // Only one task should encode a given path at a time; everyone else
+        // waits on that task's `CacheStatus` instead of racing the encoder.
+        let status = {
+            let mut in_progress = IN_PROGRESS.write().unwrap();
+            if let Some(status) = in_progress.get(&save_path) {
+                Err(status.clone())
+            } else if save_path.exists() {
+                // A leader finished writing it since the check above.
+                return Ok(false);
+            } else {
+                let status = Arc::new(CacheStatus::default());
+                in_progress.insert(save_path.clone(), status.clone());
+                Ok(status)
+            }
+        };
+
+        let status = match status {
+            // Propagate the leader's actual result: `Ok(())` really did get
+            // written to `save_path`, but an `Err` means nothing did, and
+            // the caller must see that rather than a false `Ok(false)` hit.
+            Err(status) => return status.wait_until_done().await.map(|_| false),
+            Ok(status) => status,
+        };
+
+        // Guards the in-progress entry for as long as this future lives,
+        // however it exits: normal completion, an error, or being dropped
+        // mid-`.await` by the caller (e.g. a cancelled request). See
+        // `InProgressGuard`.
+        let mut guard = InProgressGuard::new(save_path.clone(), status);
+
+        let result = self.encode_image(cache_image, root, &save_path).await;
+        guard.finish(&result);
+
+        result.map(|_| true)
+    }
+
+    /// Resolves `cache_image`'s source (downloading it first if remote) and
+    /// runs the encoder. The semaphore permit is held for the whole call so
+    /// `parallelism` also bounds concurrent remote downloads, not just the
+    /// CPU-bound decode/encode step.
+    #[cfg(feature = "ssr")]
+    async fn encode_image(
+        &self,
+        cache_image: &CachedImage,
+        root: &str,
+        save_path: &std::path::Path,
+    ) -> Result<(), CreateImageError> {
+        let _permit = self
+            .semaphore
+            .acquire()
+            .await
+            .expect("Failed to acquire semaphore");
+
+        let source = if is_remote_src(&cache_image.src) {
+            // `download_remote_image` validates the host (and every redirect
+            // hop) itself, so there's no separate pre-check here.
+            let bytes = download_remote_image(
+                &cache_image.src,
+                MAX_REMOTE_DOWNLOAD_BYTES,
+                &self.allowed_remote_hosts,
+            )
+            .await?;
+            ImageSource::Bytes(bytes)
         } else {
-            let _ = self
-                .semaphore
-                .acquire()
-                .await
-                .expect("Failed to acquire semaphore");
-            let task = tokio::task::spawn_blocking({
-                let option = cache_image.option.clone();
-                move || create_optimized_image(option, absolute_src_path, save_path)
-            });
-
-            match task.await {
-                Err(join_error) => Err(CreateImageError::JoinError(join_error)),
-                Ok(Err(err)) => Err(err),
-                Ok(Ok(_)) => Ok(true),
+            ImageSource::Path(path_from_segments(vec![root, &cache_image.src]))
+        };
+
+        let task = tokio::task::spawn_blocking({
+            let option = cache_image.option.clone();
+            let save_path = save_path.to_path_buf();
+            let cache_image = cache_image.clone();
+            move || {
+                create_optimized_image(option, source, save_path.clone())?;
+                write_spec_sidecar(&save_path, &cache_image)
             }
+        });
+
+        match task.await {
+            Err(join_error) => Err(CreateImageError::JoinError(join_error)),
+            Ok(result) => result,
         }
     }
 
+    /// Reads a local `src`'s intrinsic dimensions from disk (header only, no
+    /// full decode). Remote (`http(s)://`) sources aren't locally readable
+    /// this way; callers should skip this for them.
+    pub fn read_source_metadata(&self, src: &str) -> Result<ImageMetadata, CreateImageError> {
+        read_image_metadata(path_from_segments(vec![self.root_file_path.as_str(), src]))
+    }
+
     #[cfg(feature = "ssr")]
     pub(crate) fn get_file_path_from_root(&self, cache_image: &CachedImage) -> String {
         let path = path_from_segments(vec![
@@ -171,51 +447,172 @@ impl ImageOptimizer {
     }
 
     pub fn get_file_path(&self, cache_image: &CachedImage) -> String {
-        use base64::{engine::general_purpose, Engine as _};
-        // I'm worried this name will become too long.
-        // names are limited to 255 bytes on most filesystems.
+        // Resolved against `root_file_path` so the mtime hashed in here
+        // always matches the file this optimizer actually reads/writes.
+        // `CachedImage::get_file_path` can't do this (it has no root), so it
+        // deliberately omits the mtime rather than risk resolving the wrong
+        // file and silently hashing a different path than this one.
+        let source_path = (!is_remote_src(&cache_image.src))
+            .then(|| path_from_segments(vec![self.root_file_path.as_str(), &cache_image.src]));
+        build_cache_file_path(cache_image, source_path.as_deref())
+    }
+}
 
-        let encode = serde_qs::to_string(&cache_image).unwrap();
-        let encode = general_purpose::STANDARD.encode(encode);
+/// Builds the cache-relative path for `cache_image`, hashing in `source_path`'s
+/// mtime (if any) so the digest changes when the underlying file's bytes do.
+/// `source_path` must already be resolved by the caller (or passed as `None`
+/// if it can't be reliably resolved) — this function does no resolution
+/// itself, so `CachedImage::get_file_path` and `ImageOptimizer::get_file_path`
+/// share one hashing implementation and only differ in whether they have a
+/// root to resolve `source_path` against at all.
+#[cfg(feature = "ssr")]
+fn build_cache_file_path(cache_image: &CachedImage, source_path: Option<&std::path::Path>) -> String {
+    let hash = hash_cache_path(cache_image, source_path);
+    let mut path = path_from_segments(vec!["cache/image", &format!("{hash:016x}")]);
 
-        let mut path = path_from_segments(vec!["cache/image", &encode, &cache_image.src]);
+    match &cache_image.option {
+        CachedImageOption::Resize(Resize { format, .. }) => path.set_extension(format.extension()),
+        CachedImageOption::Crop(_) => path.set_extension("webp"),
+        CachedImageOption::Blur(_) => path.set_extension("svg"),
+    };
 
-        if let CachedImageOption::Resize { .. } = cache_image.option {
-            path.set_extension("webp");
-        } else {
-            path.set_extension("svg");
-        };
+    path.as_path().to_string_lossy().to_string()
+}
 
-        path.as_path().to_string_lossy().to_string()
+/// Hash a `CachedImage` spec together with its source file's last-modified
+/// time, so the digest changes automatically when the underlying bytes do.
+#[cfg(feature = "ssr")]
+fn hash_cache_path(cache_image: &CachedImage, source_path: Option<&std::path::Path>) -> u64 {
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    cache_image.hash(&mut hasher);
+    if let Some(modified) = source_path.and_then(|p| std::fs::metadata(p).ok()?.modified().ok()) {
+        modified.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// The sidecar file a cached image's spec is stored in, since the hashed
+/// filename itself carries no information `from_file_path` could decode.
+#[cfg(feature = "ssr")]
+fn sidecar_path(save_path: &std::path::Path) -> std::path::PathBuf {
+    let mut path = save_path.to_path_buf();
+    let file_name = format!("{}.spec.json", path.file_name().unwrap_or_default().to_string_lossy());
+    path.set_file_name(file_name);
+    path
+}
+
+#[cfg(feature = "ssr")]
+fn write_spec_sidecar(
+    save_path: &std::path::Path,
+    cache_image: &CachedImage,
+) -> Result<(), CreateImageError> {
+    create_nested_if_needed(save_path)?;
+    let json = serde_json::to_vec(cache_image)?;
+    std::fs::write(sidecar_path(save_path), json)?;
+    Ok(())
+}
+
+/// A source image's intrinsic dimensions, read without decoding pixels.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ImageMetadata {
+    pub width: u32,
+    pub height: u32,
+    pub format: Option<String>,
+}
+
+/// Read a source image's dimensions by decoding only its header, so callers
+/// can derive a missing `width`/`height` from the intrinsic aspect ratio
+/// without paying for a full pixel decode.
+#[cfg(feature = "ssr")]
+pub fn read_image_metadata<P>(path: P) -> Result<ImageMetadata, CreateImageError>
+where
+    P: AsRef<std::path::Path>,
+{
+    let reader = image::io::Reader::open(path)?.with_guessed_format()?;
+    let format = reader.format().map(|format| format!("{format:?}"));
+    let (width, height) = reader.into_dimensions()?;
+    Ok(ImageMetadata {
+        width,
+        height,
+        format,
+    })
+}
+
+/// Either a local file on disk or the downloaded bytes of a remote image,
+/// so local and optimized-remote sources share the same decode/encode path.
+#[cfg(feature = "ssr")]
+enum ImageSource {
+    Path(std::path::PathBuf),
+    Bytes(Vec<u8>),
+}
+
+#[cfg(feature = "ssr")]
+impl ImageSource {
+    fn decode(&self) -> image::ImageResult<image::DynamicImage> {
+        match self {
+            ImageSource::Path(path) => image::open(path),
+            ImageSource::Bytes(bytes) => image::load_from_memory(bytes),
+        }
     }
 }
 
 #[cfg(feature = "ssr")]
 fn create_optimized_image<P>(
     config: CachedImageOption,
-    source_path: P,
+    source: ImageSource,
     save_path: P,
 ) -> Result<(), CreateImageError>
 where
     P: AsRef<std::path::Path> + AsRef<std::ffi::OsStr>,
 {
-    use webp::*;
+    // A caller that couldn't resolve a width/height (e.g. `resolve_dimensions`
+    // falling back to `(0, 0)` for a remote source with no explicit size)
+    // would otherwise ship a degenerate spec straight to the encoder; reject
+    // it here with a clear error instead.
+    if let CachedImageOption::Resize(Resize { width, height, .. })
+    | CachedImageOption::Crop(Crop { width, height, .. }) = &config
+    {
+        if *width == 0 || *height == 0 {
+            return Err(CreateImageError::InvalidDimensions(format!(
+                "cannot create a {width}x{height} image"
+            )));
+        }
+    }
 
     match config {
         CachedImageOption::Resize(Resize {
             width,
             height,
             quality,
+            format,
         }) => {
-            let img = image::open(source_path)?;
+            let img = source.decode()?;
             let new_img = img.resize(
                 width,
                 height,
                 // Cubic Filter.
                 image::imageops::FilterType::CatmullRom,
             );
+            let bytes = encode_image(&new_img, format, quality)?;
+            create_nested_if_needed(&save_path)?;
+            std::fs::write(save_path, &bytes)?;
+
+            Ok(())
+        }
+        CachedImageOption::Crop(Crop {
+            width,
+            height,
+            quality,
+            gravity,
+        }) => {
+            use webp::*;
+
+            let img = source.decode()?;
+            let cropped = crop_to_fill(img, width, height, gravity);
             // Create the WebP encoder for the above image
-            let encoder: Encoder = Encoder::from_image(&new_img).unwrap();
+            let encoder: Encoder = Encoder::from_image(&cropped).unwrap();
             // Encode the image at a specified quality 0-100
             let webp: WebPMemory = encoder.encode(quality as f32);
             create_nested_if_needed(&save_path)?;
@@ -224,7 +621,7 @@ where
             Ok(())
         }
         CachedImageOption::Blur(blur) => {
-            let svg = create_image_blur(source_path, blur)?;
+            let svg = create_image_blur(source, blur)?;
             create_nested_if_needed(&save_path)?;
             std::fs::write(save_path, &*svg)?;
             Ok(())
@@ -232,14 +629,225 @@ where
     }
 }
 
+/// How long we'll wait on a remote host before giving up, so a slow or
+/// hanging upstream can't block the cache-stampede relay (and every task
+/// waiting on it) indefinitely.
 #[cfg(feature = "ssr")]
-fn create_image_blur<P>(source_path: P, blur: Blur) -> Result<String, CreateImageError>
-where
-    P: AsRef<std::path::Path> + AsRef<std::ffi::OsStr>,
-{
+const REMOTE_REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Maximum HTTP redirects to follow when fetching a remote image. Each hop
+/// is re-validated against the allowlist, so a redirect can't be used to
+/// reach a host (or port) that isn't allowed.
+#[cfg(feature = "ssr")]
+const MAX_REMOTE_REDIRECTS: u8 = 5;
+
+/// Parse `src` as a URL, wrapping errors in `CreateImageError`.
+#[cfg(feature = "ssr")]
+fn parse_remote_url(src: &str) -> Result<reqwest::Url, CreateImageError> {
+    reqwest::Url::parse(src).map_err(|err| CreateImageError::RemoteError(err.to_string()))
+}
+
+/// Returns an error unless `url` is on `allowed_hosts`. Used both for the
+/// initial request and for every redirect hop, so a redirect can't bypass
+/// the allowlist. An allowlist entry is either a bare host (matching only
+/// when `url` uses the scheme's default port) or a `host:port` pair
+/// (matching only that exact port) — so allowlisting a hostname doesn't
+/// implicitly allow every port it happens to be listening on.
+#[cfg(feature = "ssr")]
+fn validate_remote_host(url: &reqwest::Url, allowed_hosts: &[String]) -> Result<(), CreateImageError> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| CreateImageError::RemoteError(format!("no host in remote image url: {url}")))?;
+
+    // `Url::port()` is already `None` when the port is the scheme's default,
+    // so a bare `host` entry only ever matches a default-port URL here.
+    let candidate = match url.port() {
+        Some(port) => format!("{host}:{port}"),
+        None => host.to_string(),
+    };
+
+    if allowed_hosts.iter().any(|allowed| *allowed == candidate) {
+        Ok(())
+    } else {
+        Err(CreateImageError::RemoteError(format!(
+            "remote host not in allowlist: {candidate}"
+        )))
+    }
+}
+
+/// Download a remote image's bytes, rejecting responses larger than
+/// `max_bytes` before they're buffered for decoding. Redirects are followed
+/// manually (rather than by reqwest) so each hop can be re-validated against
+/// `allowed_hosts` before it's fetched.
+#[cfg(feature = "ssr")]
+async fn download_remote_image(
+    url: &str,
+    max_bytes: u64,
+    allowed_hosts: &[String],
+) -> Result<Vec<u8>, CreateImageError> {
+    use futures::StreamExt;
+
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .timeout(REMOTE_REQUEST_TIMEOUT)
+        .build()
+        .map_err(|err| CreateImageError::RemoteError(err.to_string()))?;
+
+    let mut current = parse_remote_url(url)?;
+    validate_remote_host(&current, allowed_hosts)?;
+
+    let mut redirects = 0u8;
+    let response = loop {
+        let response = client
+            .get(current.clone())
+            .send()
+            .await
+            .map_err(|err| CreateImageError::RemoteError(err.to_string()))?;
+
+        if !response.status().is_redirection() {
+            break response;
+        }
+
+        redirects += 1;
+        if redirects > MAX_REMOTE_REDIRECTS {
+            return Err(CreateImageError::RemoteError(format!(
+                "remote image at {url} redirected more than {MAX_REMOTE_REDIRECTS} times"
+            )));
+        }
+
+        let location = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .ok_or_else(|| CreateImageError::RemoteError(format!("redirect from {current} had no Location header")))?
+            .to_str()
+            .map_err(|err| CreateImageError::RemoteError(err.to_string()))?;
+
+        current = current
+            .join(location)
+            .map_err(|err| CreateImageError::RemoteError(err.to_string()))?;
+        validate_remote_host(&current, allowed_hosts)?;
+    };
+
+    if let Some(len) = response.content_length() {
+        if len > max_bytes {
+            return Err(CreateImageError::RemoteError(format!(
+                "remote image at {url} is {len} bytes, exceeding the {max_bytes} byte limit"
+            )));
+        }
+    }
+
+    let mut bytes = Vec::new();
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|err| CreateImageError::RemoteError(err.to_string()))?;
+        if bytes.len() + chunk.len() > max_bytes as usize {
+            return Err(CreateImageError::RemoteError(format!(
+                "remote image at {url} exceeds the {max_bytes} byte limit"
+            )));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    Ok(bytes)
+}
+
+/// Encode a decoded image to bytes in the requested target format.
+#[cfg(feature = "ssr")]
+fn encode_image(
+    img: &image::DynamicImage,
+    format: ImageFormat,
+    quality: u8,
+) -> Result<Vec<u8>, CreateImageError> {
     use webp::*;
 
-    let img = image::open(source_path).map_err(|e| CreateImageError::ImageError(e))?;
+    match format {
+        ImageFormat::WebP => {
+            // Create the WebP encoder for the above image
+            let encoder: Encoder = Encoder::from_image(img).unwrap();
+            // Encode the image at a specified quality 0-100
+            let webp: WebPMemory = encoder.encode(quality as f32);
+            Ok(webp.to_vec())
+        }
+        ImageFormat::Jpeg => {
+            let mut bytes = Vec::new();
+            let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut bytes, quality);
+            img.write_with_encoder(encoder)?;
+            Ok(bytes)
+        }
+        ImageFormat::Png => {
+            let mut bytes = Vec::new();
+            let encoder = image::codecs::png::PngEncoder::new(&mut bytes);
+            img.write_with_encoder(encoder)?;
+            Ok(bytes)
+        }
+        ImageFormat::Avif => encode_avif(img, quality),
+    }
+}
+
+/// Encode a decoded image as AVIF using a dedicated AV1-based encoder
+/// (the `image` crate has no first-class AVIF encoder).
+#[cfg(feature = "ssr")]
+fn encode_avif(img: &image::DynamicImage, quality: u8) -> Result<Vec<u8>, CreateImageError> {
+    let rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    let pixels: Vec<ravif::RGBA8> = rgba
+        .pixels()
+        .map(|p| ravif::RGBA8::new(p[0], p[1], p[2], p[3]))
+        .collect();
+    let buffer = ravif::Img::new(pixels.as_slice(), width as usize, height as usize);
+
+    let result = ravif::Encoder::new()
+        .with_quality(quality as f32)
+        .with_alpha_quality(quality as f32)
+        .encode_rgba(buffer)
+        .map_err(|err| CreateImageError::AvifError(err.to_string()))?;
+
+    Ok(result.avif_file)
+}
+
+/// Scale `img` so its shorter side fills the `width`x`height` box, then crop
+/// the overhang according to `gravity`, producing a distortion-free
+/// fixed-aspect thumbnail.
+#[cfg(feature = "ssr")]
+fn crop_to_fill(
+    img: image::DynamicImage,
+    width: u32,
+    height: u32,
+    gravity: Gravity,
+) -> image::DynamicImage {
+    let scale = (width as f64 / img.width() as f64).max(height as f64 / img.height() as f64);
+    let cover_width = (img.width() as f64 * scale).round() as u32;
+    let cover_height = (img.height() as f64 * scale).round() as u32;
+
+    let mut resized = img.resize_exact(
+        cover_width,
+        cover_height,
+        image::imageops::FilterType::CatmullRom,
+    );
+
+    let max_x = cover_width.saturating_sub(width);
+    let max_y = cover_height.saturating_sub(height);
+
+    let (x, y) = match gravity {
+        Gravity::Center => (max_x / 2, max_y / 2),
+        Gravity::North => (max_x / 2, 0),
+        Gravity::NorthEast => (max_x, 0),
+        Gravity::East => (max_x, max_y / 2),
+        Gravity::SouthEast => (max_x, max_y),
+        Gravity::South => (max_x / 2, max_y),
+        Gravity::SouthWest => (0, max_y),
+        Gravity::West => (0, max_y / 2),
+        Gravity::NorthWest => (0, 0),
+    };
+
+    image::DynamicImage::ImageRgba8(image::imageops::crop(&mut resized, x, y, width, height).to_image())
+}
+
+#[cfg(feature = "ssr")]
+fn create_image_blur(source: ImageSource, blur: Blur) -> Result<String, CreateImageError> {
+    use webp::*;
+
+    let img = source.decode()?;
 
     let Blur {
         width,
@@ -322,6 +930,7 @@ mod optimizer_tests {
                 quality: 75,
                 width: 100,
                 height: 100,
+                format: ImageFormat::WebP,
             }),
         };
 
@@ -349,7 +958,9 @@ mod optimizer_tests {
 
         let file_path = spec.get_file_path();
 
-        dbg!(spec.get_file_path());
+        // `from_file_path` reconstructs the spec from its sidecar, so write
+        // one the way `ImageOptimizer::create_image` would.
+        write_spec_sidecar(std::path::Path::new(&file_path), &spec).unwrap();
 
         let result = CachedImage::from_file_path(&file_path).unwrap();
 
@@ -359,7 +970,7 @@ mod optimizer_tests {
     #[test]
     fn create_blur() {
         let result = create_image_blur(
-            TEST_IMAGE.to_string(),
+            ImageSource::Path(TEST_IMAGE.into()),
             Blur {
                 width: 25,
                 height: 25,
@@ -387,7 +998,7 @@ mod optimizer_tests {
 
         let file_path = spec.get_file_path();
 
-        let result = create_optimized_image(spec.option, TEST_IMAGE.to_string(), file_path.clone());
+        let result = create_optimized_image(spec.option, ImageSource::Path(TEST_IMAGE.into()), file_path.clone());
 
         assert!(result.is_ok());
 
@@ -402,15 +1013,180 @@ mod optimizer_tests {
                 quality: 75,
                 width: 100,
                 height: 100,
+                format: ImageFormat::WebP,
             }),
         };
 
         let file_path = spec.get_file_path();
 
-        let result = create_optimized_image(spec.option, TEST_IMAGE.to_string(), file_path.clone());
+        let result = create_optimized_image(spec.option, ImageSource::Path(TEST_IMAGE.into()), file_path.clone());
 
         assert!(result.is_ok());
 
         println!("Saved WebP at {file_path}");
     }
+
+    #[test]
+    fn create_crop_image() {
+        // A non-square, non-`Center` target so the test would also catch a
+        // regression in `crop_to_fill`'s gravity offset math, not just its
+        // cover-scale step.
+        let spec = CachedImage {
+            src: TEST_IMAGE.to_string(),
+            option: CachedImageOption::Crop(Crop {
+                quality: 75,
+                width: 50,
+                height: 150,
+                gravity: Gravity::North,
+            }),
+        };
+
+        let file_path = spec.get_file_path();
+
+        let result = create_optimized_image(spec.option, ImageSource::Path(TEST_IMAGE.into()), file_path.clone());
+        assert!(result.is_ok());
+
+        let cropped = image::open(&file_path).unwrap();
+        assert_eq!(cropped.width(), 50);
+        assert_eq!(cropped.height(), 150);
+
+        println!("Saved WebP crop at {file_path}");
+    }
+
+    #[tokio::test]
+    async fn create_image_dedupes_concurrent_callers() {
+        let optimizer = ImageOptimizer::new(String::new(), 4);
+        let spec = CachedImage {
+            src: TEST_IMAGE.to_string(),
+            option: CachedImageOption::Resize(Resize {
+                quality: 75,
+                width: 64,
+                height: 64,
+                format: ImageFormat::WebP,
+            }),
+        };
+
+        // Start from a clean slate so both callers race to create the file.
+        let save_path = optimizer.get_file_path_from_root(&spec);
+        let _ = std::fs::remove_file(&save_path);
+        let _ = std::fs::remove_file(sidecar_path(std::path::Path::new(&save_path)));
+
+        let (a, b) = tokio::join!(optimizer.create_image(&spec), optimizer.create_image(&spec));
+        let a = a.unwrap();
+        let b = b.unwrap();
+
+        // Exactly one caller should be the leader that actually encoded the
+        // file; the other must be relayed through `CacheStatus` rather than
+        // racing the encoder or re-encoding redundantly.
+        assert!(a != b, "expected exactly one leader, got {a} and {b}");
+        assert!(std::fs::metadata(&save_path).is_ok());
+    }
+
+    #[test]
+    fn validate_remote_host_allows_listed_host_on_default_port() {
+        let url = reqwest::Url::parse("https://images.example.com/a.png").unwrap();
+        let allowed = vec!["images.example.com".to_string()];
+        assert!(validate_remote_host(&url, &allowed).is_ok());
+    }
+
+    #[test]
+    fn validate_remote_host_rejects_unlisted_host() {
+        let url = reqwest::Url::parse("https://evil.example.com/a.png").unwrap();
+        let allowed = vec!["images.example.com".to_string()];
+        assert!(validate_remote_host(&url, &allowed).is_err());
+    }
+
+    #[test]
+    fn validate_remote_host_rejects_non_standard_port_without_explicit_entry() {
+        let url = reqwest::Url::parse("https://images.example.com:8443/a.png").unwrap();
+        let allowed = vec!["images.example.com".to_string()];
+        assert!(validate_remote_host(&url, &allowed).is_err());
+    }
+
+    #[test]
+    fn validate_remote_host_allows_non_standard_port_with_explicit_entry() {
+        let url = reqwest::Url::parse("https://images.example.com:8443/a.png").unwrap();
+        let allowed = vec!["images.example.com:8443".to_string()];
+        assert!(validate_remote_host(&url, &allowed).is_ok());
+    }
+
+    /// Spins up a minimal one-shot-per-connection HTTP server on localhost
+    /// that replies to every request with a canned response, so remote-fetch
+    /// behavior can be exercised without a real network dependency.
+    async fn spawn_test_http_server(response: String) -> std::net::SocketAddr {
+        use tokio::io::AsyncWriteExt;
+        use tokio::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn download_remote_image_fetches_from_allowed_host() {
+        let addr = spawn_test_http_server(
+            "HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello".to_string(),
+        )
+        .await;
+        let url = format!("http://{addr}/image.png");
+        let allowed = vec![format!("{}:{}", addr.ip(), addr.port())];
+        let bytes = download_remote_image(&url, 1024, &allowed).await.unwrap();
+        assert_eq!(bytes, b"hello");
+    }
+
+    #[tokio::test]
+    async fn download_remote_image_follows_redirect_to_allowed_host() {
+        let target_addr = spawn_test_http_server(
+            "HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello".to_string(),
+        )
+        .await;
+        let origin_addr = spawn_test_http_server(format!(
+            "HTTP/1.1 302 Found\r\nLocation: http://{target_addr}/\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        ))
+        .await;
+        let url = format!("http://{origin_addr}/image.png");
+        let allowed = vec![
+            format!("{}:{}", origin_addr.ip(), origin_addr.port()),
+            format!("{}:{}", target_addr.ip(), target_addr.port()),
+        ];
+        let bytes = download_remote_image(&url, 1024, &allowed).await.unwrap();
+        assert_eq!(bytes, b"hello");
+    }
+
+    #[tokio::test]
+    async fn download_remote_image_rejects_redirect_to_disallowed_host() {
+        let origin_addr = spawn_test_http_server(
+            "HTTP/1.1 302 Found\r\nLocation: http://evil.internal/\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                .to_string(),
+        )
+        .await;
+        let url = format!("http://{origin_addr}/image.png");
+        let allowed = vec![format!("{}:{}", origin_addr.ip(), origin_addr.port())];
+        let result = download_remote_image(&url, 1024, &allowed).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn download_remote_image_rejects_too_many_redirects() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let response = format!(
+            "HTTP/1.1 302 Found\r\nLocation: http://{addr}/\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        );
+        tokio::spawn(async move {
+            use tokio::io::AsyncWriteExt;
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        let url = format!("http://{addr}/image.png");
+        let allowed = vec![format!("{}:{}", addr.ip(), addr.port())];
+        let result = download_remote_image(&url, 1024, &allowed).await;
+        assert!(result.is_err());
+    }
 }